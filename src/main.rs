@@ -1,92 +1,532 @@
+mod output;
+mod platform;
+
 use anyhow::{Context, Result};
-use clap::{Arg, Command as ClapCommand};
+use clap::{Arg, ArgAction, Command as ClapCommand};
 use futures::future::try_join_all;
+use output::OutputOptions;
+use platform::ProcessHandle;
 use std::collections::HashSet;
 use std::process::Stdio;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::process::Command;
-use tokio::sync::Mutex;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::{watch, Mutex};
+use tokio::time::sleep;
+
+// Track the platform-specific handle (process group on Unix, Job Object on Windows)
+// of all running chains, so that killing it also reaps any grandchildren it spawned.
+type ProcessRegistry = Arc<Mutex<HashSet<ProcessHandle>>>;
+
+/// A `(program, args)` pair to spawn for a single pipeline stage.
+type Stage = (String, Vec<String>);
+/// The stages of one chain step: a lone command, or several piped together.
+type Step = Vec<Stage>;
+/// A sequence of steps run one after another.
+type Chain = Vec<Step>;
+/// Every chain, each of which runs in parallel with the others.
+type ChainSet = Vec<Chain>;
+
+/// Dedicated token connecting pipeline stages within a chain step, e.g.
+/// `spawny :: producer '|' consumer`. Distinct from `separator`/`seq_separator`.
+const PIPE_TOKEN: &str = "|";
+
+/// Which shell, if any, a sub-group's tokens are rejoined and passed to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Shell {
+    /// Spawn `program` with `args` directly (current behavior).
+    None,
+    /// Rejoin the tokens and run them through the named Unix shell (`sh -c "..."`).
+    Unix(String),
+    /// Rejoin the tokens and run them through `cmd /C "..."`.
+    Cmd,
+    /// Rejoin the tokens and run them through `powershell -Command "..."`.
+    Pwsh,
+}
+
+impl Shell {
+    fn parse(value: &str) -> Shell {
+        match value {
+            "none" => Shell::None,
+            "cmd" => Shell::Cmd,
+            "powershell" => Shell::Pwsh,
+            shell => Shell::Unix(shell.to_string()),
+        }
+    }
+}
+
+/// Whether a failed (or, for `Always`, any) step in a chain should be restarted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RestartPolicy {
+    /// Never restart; tear down the whole run on the first failure (current behavior).
+    Never,
+    /// Restart a step only when it exits with a non-zero status.
+    OnFailure,
+    /// Restart a step every time it exits, success or failure.
+    Always,
+}
+
+impl RestartPolicy {
+    fn parse(value: &str) -> Result<RestartPolicy> {
+        match value {
+            "never" => Ok(RestartPolicy::Never),
+            "on-failure" => Ok(RestartPolicy::OnFailure),
+            "always" => Ok(RestartPolicy::Always),
+            other => anyhow::bail!(
+                "invalid --restart value '{}', expected 'never', 'on-failure' or 'always'",
+                other
+            ),
+        }
+    }
+}
 
-// Track all running processes
-type ProcessRegistry = Arc<Mutex<HashSet<u32>>>;
+/// Settings shared by every process spawned across all chains.
+#[derive(Clone)]
+struct RunConfig {
+    kill_timeout: Duration,
+    shell: Shell,
+    output: OutputOptions,
+    /// Set once the printer task is running, so each process can forward its output to it.
+    line_tx: Option<UnboundedSender<output::Line>>,
+    restart: RestartPolicy,
+    max_restarts: Option<u32>,
+    restart_backoff: Duration,
+    /// Flips to `true` once the program is shutting down (e.g. on Ctrl-C), so a
+    /// restart loop that would otherwise run forever stops instead of outliving it.
+    shutdown: watch::Receiver<bool>,
+}
+
+/// Builds the `(program, args)` that should actually be spawned for a sub-group,
+/// rejoining its tokens into a single string for the configured shell if any.
+fn shell_command(program: &str, args: &[String], shell: &Shell) -> (String, Vec<String>) {
+    if *shell == Shell::None {
+        return (program.to_string(), args.to_vec());
+    }
+
+    let joined = std::iter::once(program)
+        .chain(args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    match shell {
+        Shell::None => unreachable!(),
+        Shell::Unix(shell) => (shell.clone(), vec!["-c".to_string(), joined]),
+        Shell::Cmd => ("cmd".to_string(), vec!["/C".to_string(), joined]),
+        Shell::Pwsh => ("powershell".to_string(), vec!["-Command".to_string(), joined]),
+    }
+}
 
-async fn execute_process(program: &str, args: &[String], registry: ProcessRegistry) -> Result<()> {
+async fn execute_process(
+    program: &str,
+    args: &[String],
+    registry: ProcessRegistry,
+    config: &RunConfig,
+) -> Result<()> {
     println!("Executing {} with args {:?}", program, args);
 
-    let mut child = Command::new(program)
-        .args(args)
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
+    let (spawn_program, spawn_args) = shell_command(program, args, &config.shell);
+
+    let mut command = Command::new(&spawn_program);
+    command.args(&spawn_args);
+
+    if config.output.prefix {
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    } else {
+        command.stdout(Stdio::inherit()).stderr(Stdio::inherit());
+    }
+
+    // Let the platform module set up whatever is needed to terminate the
+    // whole subtree later, not just the immediate child.
+    platform::configure_spawn(&mut command);
+
+    let mut child = command
         .spawn()
-        .with_context(|| format!("Failed to spawn {}", program))?;
+        .with_context(|| format!("Failed to spawn {}", spawn_program))?;
 
-    // Register the process ID
-    let pid = child.id().expect("Failed to get process ID");
-    registry.lock().await.insert(pid);
+    let handle = platform::register(&child)?;
+    registry.lock().await.insert(handle);
 
-    let status = child
-        .wait()
-        .await
-        .with_context(|| format!("Failed to wait for {}", program))?;
+    if config.output.prefix {
+        let stdout = child.stdout.take().expect("stdout was piped");
+        let stderr = child.stderr.take().expect("stderr was piped");
+        let tx = config
+            .line_tx
+            .clone()
+            .expect("line channel is set whenever output prefixing is enabled");
+        output::spawn_readers(program.to_string(), output::next_color(), stdout, stderr, tx);
+    }
+
+    let mut shutdown = config.shutdown.clone();
+    let status = tokio::select! {
+        status = child.wait() => status.with_context(|| format!("Failed to wait for {}", spawn_program))?,
+        _ = wait_for_shutdown(&mut shutdown) => {
+            // kill_all_processes (triggered by the same shutdown signal) is
+            // responsible for actually terminating it; just stop waiting here
+            // and reap it in the background so it doesn't linger as a zombie.
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                let _ = child.wait().await;
+                forget_handle(&registry, handle).await;
+            });
+            anyhow::bail!("shutdown requested while running {}", program);
+        }
+    };
 
-    // Remove process from registry after it completes
-    registry.lock().await.remove(&pid);
+    // Remove the group from the registry once it has exited on its own.
+    forget_handle(&registry, handle).await;
 
     if !status.success() {
-        kill_all_processes(&registry).await;
+        // Whether to tear down the rest of the chain or restart this step is a
+        // policy decision left to the caller (`run_step_with_restarts`).
         anyhow::bail!("Process {} exited with: {}", program, status);
     }
 
     Ok(())
 }
 
-async fn kill_all_processes(registry: &ProcessRegistry) {
-    let pids: Vec<u32> = registry.lock().await.iter().copied().collect();
-    for pid in pids {
-        unsafe {
-            libc::kill(pid as i32, libc::SIGTERM);
+/// Removes a handle from the registry and releases whatever platform resources it
+/// holds (e.g. a Windows Job Object handle), but only once: if another task already
+/// removed it (e.g. `kill_all_processes` racing a process's own exit), this is a no-op.
+async fn forget_handle(registry: &ProcessRegistry, handle: ProcessHandle) {
+    if registry.lock().await.remove(&handle) {
+        platform::release(handle);
+    }
+}
+
+/// Terminates every process group still in the registry. On Unix each group is first
+/// sent `SIGTERM`; any group still alive after `kill_timeout` is escalated to `SIGKILL`.
+/// On Windows, terminating the Job Object kills the whole tree in one step, so the
+/// second pass is just a harmless no-op.
+async fn kill_all_processes(registry: &ProcessRegistry, kill_timeout: Duration) {
+    let handles: Vec<ProcessHandle> = registry.lock().await.iter().copied().collect();
+    if handles.is_empty() {
+        return;
+    }
+
+    for handle in &handles {
+        platform::terminate(*handle, false);
+    }
+
+    sleep(kill_timeout).await;
+
+    let remaining: Vec<ProcessHandle> = registry.lock().await.iter().copied().collect();
+    for handle in remaining {
+        platform::terminate(handle, true);
+    }
+
+    for handle in registry.lock().await.drain() {
+        platform::release(handle);
+    }
+}
+
+/// Waits until `shutdown` carries `true`, tolerating a receiver that was cloned
+/// after the flag was already flipped (a plain `.changed()` call would hang
+/// forever in that case, since that clone has already "seen" the only change).
+async fn wait_for_shutdown(shutdown: &mut watch::Receiver<bool>) {
+    while !*shutdown.borrow() {
+        if shutdown.changed().await.is_err() {
+            // The sender was dropped without ever signalling; nothing to wait for.
+            return;
         }
     }
-    registry.lock().await.clear();
 }
 
-async fn execute_sequential_chain(
-    chain: &[(String, Vec<String>)],
+/// Runs a single chain step (a lone process, or a pipeline of stages) to completion,
+/// restarting it according to `config.restart` when it fails (or, under
+/// `RestartPolicy::Always`, whenever it exits) until the policy gives up or
+/// `config.max_restarts` is exhausted.
+async fn run_step_with_restarts(
+    step: &[Stage],
     registry: ProcessRegistry,
+    config: &RunConfig,
 ) -> Result<()> {
-    for (i, (program, args)) in chain.iter().enumerate() {
-        if let Err(e) = execute_process(program, args, registry.clone()).await {
-            kill_all_processes(&registry).await;
+    let label = step
+        .iter()
+        .map(|(program, _)| program.as_str())
+        .collect::<Vec<_>>()
+        .join(&format!(" {} ", PIPE_TOKEN));
+
+    let mut attempt = 0u32;
+    loop {
+        let result = match step {
+            [(program, args)] => execute_process(program, args, registry.clone(), config).await,
+            stages => execute_pipeline(stages, registry.clone(), config).await,
+        };
+
+        let should_restart = match config.restart {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => result.is_err(),
+            RestartPolicy::Always => true,
+        } && config.max_restarts.is_none_or(|max| attempt < max);
+
+        if !should_restart {
+            return result;
+        }
+
+        if let Err(e) = &result {
+            eprintln!("{:#}", e);
+        }
+
+        if *config.shutdown.borrow() {
+            anyhow::bail!("shutdown requested, not restarting {}", label);
+        }
+
+        attempt += 1;
+        let backoff = config.restart_backoff * 2u32.pow(attempt.min(6) - 1);
+        println!("Restarting {} (attempt {}) in {:?}", label, attempt, backoff);
+
+        // Cut the backoff short if a shutdown comes in while waiting, rather than
+        // restarting this step anyway once the sleep finally elapses.
+        let mut shutdown = config.shutdown.clone();
+        tokio::select! {
+            _ = sleep(backoff) => {}
+            _ = shutdown.changed() => {}
+        }
+
+        if *config.shutdown.borrow() {
+            anyhow::bail!("shutdown requested while waiting to restart {}", label);
+        }
+    }
+}
+
+/// Spawns every stage of a pipeline, wiring each stage's stdout to the next stage's
+/// stdin via an OS pipe. The pipeline's success is the last stage's exit status;
+/// every stage's group is registered so `kill_all_processes` can reach it.
+async fn execute_pipeline(stages: &[Stage], registry: ProcessRegistry, config: &RunConfig) -> Result<()> {
+    if stages.is_empty() {
+        anyhow::bail!("pipeline step has no commands (check for a stray '{}')", PIPE_TOKEN);
+    }
+
+    let last = stages.len() - 1;
+    let mut stage_children = Vec::with_capacity(stages.len());
+    let mut next_stdin: Option<Stdio> = None;
+
+    for (i, (program, args)) in stages.iter().enumerate() {
+        println!("Executing pipeline stage {} with args {:?}", program, args);
+
+        let (spawn_program, spawn_args) = shell_command(program, args, &config.shell);
+        let is_last = i == last;
+        // An intermediate stage's stdout always needs to be a pipe (it feeds the next
+        // stage); the last stage's stdout only needs to be piped back here if its
+        // output is meant to be prefixed instead of inherited directly.
+        let capture_stdout = !is_last || config.output.prefix;
+
+        let mut command = Command::new(&spawn_program);
+        command
+            .args(&spawn_args)
+            .stdin(next_stdin.take().unwrap_or_else(Stdio::inherit))
+            .stdout(if capture_stdout { Stdio::piped() } else { Stdio::inherit() })
+            .stderr(if config.output.prefix { Stdio::piped() } else { Stdio::inherit() });
+
+        platform::configure_spawn(&mut command);
+
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to spawn {}", spawn_program))?;
+
+        let handle = platform::register(&child)?;
+        registry.lock().await.insert(handle);
+
+        let stderr = config.output.prefix.then(|| child.stderr.take().expect("stderr was piped"));
+
+        if !is_last {
+            let stdout = child.stdout.take().expect("stdout was piped");
+            let stdio: Stdio = stdout
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("failed to hand {} stdout to the next stage", program))?;
+            next_stdin = Some(stdio);
+
+            if let Some(stderr) = stderr {
+                let tx = config
+                    .line_tx
+                    .clone()
+                    .expect("line channel is set whenever output prefixing is enabled");
+                output::spawn_stderr_reader(program.clone(), output::next_color(), stderr, tx);
+            }
+        } else if config.output.prefix {
+            let stdout = child.stdout.take().expect("stdout was piped");
+            let tx = config
+                .line_tx
+                .clone()
+                .expect("line channel is set whenever output prefixing is enabled");
+            output::spawn_readers(
+                program.clone(),
+                output::next_color(),
+                stdout,
+                stderr.expect("stderr was piped"),
+                tx,
+            );
+        }
+
+        stage_children.push((handle, child));
+    }
+
+    let (last_handle, mut last_child) = stage_children.remove(last);
+    let rest_handles: Vec<ProcessHandle> = stage_children.iter().map(|(handle, _)| *handle).collect();
+
+    // Reap the other stages (e.g. an upstream producer) concurrently in the
+    // background instead of one at a time in order: waiting on an earlier stage
+    // before the last one would hang the whole pipeline if that stage outlives it.
+    let rest_reapers: Vec<_> = stage_children
+        .into_iter()
+        .map(|(handle, mut child)| {
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                let _ = child.wait().await;
+                forget_handle(&registry, handle).await;
+            })
+        })
+        .collect();
+
+    let mut shutdown = config.shutdown.clone();
+    let last_status = tokio::select! {
+        status = last_child.wait() => status.context("Failed to wait for pipeline stage")?,
+        _ = wait_for_shutdown(&mut shutdown) => {
+            // kill_all_processes (triggered by the same shutdown signal) is
+            // responsible for actually terminating it; just stop waiting here
+            // and reap it in the background so it doesn't linger as a zombie.
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                let _ = last_child.wait().await;
+                forget_handle(&registry, last_handle).await;
+            });
+            for reaper in rest_reapers {
+                let _ = reaper.await;
+            }
+            anyhow::bail!("shutdown requested while running pipeline");
+        }
+    };
+    forget_handle(&registry, last_handle).await;
+
+    // The pipeline's result is decided by the last stage; sweep whatever's still
+    // running upstream the same way kill_all_processes does, SIGTERM first and
+    // escalating to SIGKILL for anything still alive after kill_timeout, rather
+    // than firing a single SIGTERM and hoping every stage honors it.
+    for handle in &rest_handles {
+        platform::terminate(*handle, false);
+    }
+
+    sleep(config.kill_timeout).await;
+
+    let still_running: Vec<ProcessHandle> = {
+        let registry = registry.lock().await;
+        rest_handles.iter().copied().filter(|handle| registry.contains(handle)).collect()
+    };
+    for handle in still_running {
+        platform::terminate(handle, true);
+    }
+
+    for reaper in rest_reapers {
+        let _ = reaper.await;
+    }
+
+    if last_status.success() {
+        Ok(())
+    } else {
+        anyhow::bail!("Pipeline exited with: {}", last_status);
+    }
+}
+
+async fn execute_sequential_chain(chain: &[Step], registry: ProcessRegistry, config: &RunConfig) -> Result<()> {
+    for (i, step) in chain.iter().enumerate() {
+        if let Err(e) = run_step_with_restarts(step, registry.clone(), config).await {
+            kill_all_processes(&registry, config.kill_timeout).await;
             return Err(e);
         }
 
         // If this was the last process in the chain, kill all remaining processes
         if i == chain.len() - 1 {
             println!("Chain completed successfully, terminating all processes");
-            kill_all_processes(&registry).await;
+            kill_all_processes(&registry, config.kill_timeout).await;
             return Ok(());
         }
     }
     Ok(())
 }
 
-async fn execute_process_chains(process_chains: Vec<Vec<(String, Vec<String>)>>) -> Result<()> {
-    let registry: ProcessRegistry = Arc::new(Mutex::new(HashSet::new()));
+/// Splits the flat `commands` token list into the parallel/sequential/pipeline
+/// grammar: tokens are split on `separator` into parallel chains, each chain is
+/// split on the doubled separator into sequential steps, and each step is split
+/// on `|` into pipeline stages. Empty groups at any level (e.g. from a leading,
+/// trailing, or doubled-up separator) are dropped.
+fn parse_process_chains(commands: &[String], separator: &str, seq_separator: &str) -> ChainSet {
+    commands
+        .split(|token| token == separator)
+        .filter(|group| !group.is_empty())
+        .map(|group| {
+            group
+                .split(|token| token == seq_separator)
+                .filter(|sub_group| !sub_group.is_empty())
+                .map(|sub_group| {
+                    sub_group
+                        .split(|token| token == PIPE_TOKEN)
+                        .filter(|stage| !stage.is_empty())
+                        .map(|stage| {
+                            let (program, args) = stage.split_first().unwrap();
+                            (
+                                program.to_string(),
+                                args.iter().map(|s| s.to_string()).collect(),
+                            )
+                        })
+                        .collect()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+async fn execute_process_chains(
+    process_chains: ChainSet,
+    mut config: RunConfig,
+    registry: ProcessRegistry,
+) -> Result<()> {
+    let printer = if config.output.prefix {
+        let (tx, handle) = output::spawn_printer(config.output.color);
+        config.line_tx = Some(tx);
+        Some(handle)
+    } else {
+        None
+    };
 
     // Convert each chain into a future that executes its processes sequentially
     let chain_futures: Vec<_> = process_chains
         .iter()
-        .map(|chain| execute_sequential_chain(chain, registry.clone()))
+        .map(|chain| execute_sequential_chain(chain, registry.clone(), &config))
         .collect();
 
     // We use try_join_all to execute all chains in parallel
     // When any chain completes (success or error), all processes will be killed
-    if let Err(e) = try_join_all(chain_futures).await {
-        // Error case is already handled in execute_sequential_chain
-        return Err(e);
+    let result = try_join_all(chain_futures).await;
+
+    // Drop the last sender so the printer task drains any remaining lines and exits.
+    config.line_tx = None;
+    if let Some(printer) = printer {
+        let _ = printer.await;
     }
 
-    Ok(())
+    // Error case is already handled in execute_sequential_chain
+    result.map(|_| ())
+}
+
+/// Waits for Ctrl-C (`SIGINT`), or, on Unix, `SIGTERM` — whichever arrives first.
+/// Process-group isolation (see `platform::configure_spawn`) means children no
+/// longer receive either signal on their own, so this is the only thing that
+/// triggers tearing them down when spawny itself is asked to stop.
+async fn wait_for_termination_signal() {
+    #[cfg(unix)]
+    {
+        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = sigterm.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 }
 
 #[tokio::main]
@@ -100,6 +540,70 @@ async fn main() -> Result<()> {
                 .required(true)
                 .help("Separator token (e.g. '::')"),
         )
+        .arg(
+            Arg::new("kill-timeout")
+                .long("kill-timeout")
+                .value_name("SECONDS")
+                .default_value("2")
+                .help("Seconds to wait after SIGTERM before escalating to SIGKILL"),
+        )
+        .arg(
+            Arg::new("shell")
+                .long("shell")
+                .value_name("SHELL")
+                .default_value("none")
+                .help(
+                    "Interpret each sub-group's tokens through a shell instead of spawning \
+                     the program directly: 'none' (default), 'cmd', 'powershell', or a Unix \
+                     shell name/path such as 'sh' or 'bash'",
+                ),
+        )
+        .arg(
+            Arg::new("no-prefix")
+                .long("no-prefix")
+                .action(ArgAction::SetTrue)
+                .overrides_with("prefix")
+                .help("Disable per-process line prefixing (original inherit-style output)"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .long("prefix")
+                .action(ArgAction::SetTrue)
+                .overrides_with("no-prefix")
+                .help("Enable per-process line prefixing (default)"),
+        )
+        .arg(
+            Arg::new("no-color")
+                .long("no-color")
+                .action(ArgAction::SetTrue)
+                .help("Disable colorizing prefixed output"),
+        )
+        .arg(
+            Arg::new("raw")
+                .long("raw")
+                .action(ArgAction::SetTrue)
+                .help("Shorthand for --no-prefix --no-color"),
+        )
+        .arg(
+            Arg::new("restart")
+                .long("restart")
+                .value_name("POLICY")
+                .default_value("never")
+                .help("Restart policy for a step: 'never' (default), 'on-failure', or 'always' (also after a successful exit)"),
+        )
+        .arg(
+            Arg::new("max-restarts")
+                .long("max-restarts")
+                .value_name("N")
+                .help("Maximum number of restarts per step (unlimited if omitted)"),
+        )
+        .arg(
+            Arg::new("restart-backoff")
+                .long("restart-backoff")
+                .value_name("MILLISECONDS")
+                .default_value("500")
+                .help("Initial backoff before a restart, doubled after each attempt up to 32 times the initial value"),
+        )
         .arg(
             Arg::new("commands")
                 .num_args(1..)
@@ -110,7 +614,7 @@ async fn main() -> Result<()> {
                     "Programs and arguments separated by the separator
 <separator> <prog1> <args1...> <separator> <prog2> <args2> <separator> ... <progN> <argsN>
 
-The actual separator doubled means that the following command will be executed sequentially 
+The actual separator doubled means that the following command will be executed sequentially
 when the previous command finishes. The default separator :: as :::: is a sequential separator.
 
 When a chain of commands (or the single command) executed in parallel finishes, the whole
@@ -123,6 +627,10 @@ spawny -:- hello -:- world --doit
 spawny :: gedit :: meld
 # another example: delayed execution of the client after the server started
 spawny :: server --some-param --another-param=x :: sleep 2 :::: client -param
+# use shell features (globs, pipes, env expansion) inside a command
+spawny --shell sh :: \"cat *.log | grep error\" :: tail -f out
+# wire producer's stdout into consumer's stdin within one chain step
+spawny :: producer '|' consumer
 
 The separator could be any character except special characters (inside a shell).
 See https://mywiki.wooledge.org/BashGuide/SpecialCharacters
@@ -134,29 +642,174 @@ See https://mywiki.wooledge.org/BashGuide/SpecialCharacters
     let separator = matches.get_one::<String>("separator").unwrap();
     let seq_separator = [separator.clone(), separator.clone()].join("");
     let commands: Vec<String> = matches.get_many("commands").unwrap().cloned().collect();
+    let kill_timeout = Duration::from_secs(
+        matches
+            .get_one::<String>("kill-timeout")
+            .unwrap()
+            .parse()
+            .context("--kill-timeout must be a whole number of seconds")?,
+    );
+    let shell = Shell::parse(matches.get_one::<String>("shell").unwrap());
+    let output = OutputOptions::new(
+        matches.get_flag("no-prefix"),
+        matches.get_flag("no-color"),
+        matches.get_flag("raw"),
+    );
+    let restart = RestartPolicy::parse(matches.get_one::<String>("restart").unwrap())?;
+    let max_restarts = matches
+        .get_one::<String>("max-restarts")
+        .map(|v| v.parse::<u32>())
+        .transpose()
+        .context("--max-restarts must be a whole number")?;
+    let restart_backoff = Duration::from_millis(
+        matches
+            .get_one::<String>("restart-backoff")
+            .unwrap()
+            .parse()
+            .context("--restart-backoff must be a whole number of milliseconds")?,
+    );
+    let registry: ProcessRegistry = Arc::new(Mutex::new(HashSet::new()));
 
-    let process_chains: Vec<Vec<(String, Vec<String>)>> = commands
-        .split(|token| token == separator)
-        .filter(|group| !group.is_empty())
-        .map(|group| {
-            group
-                .split(|token| token == &seq_separator)
-                .filter(|sub_group| !sub_group.is_empty())
-                .map(|sub_group| {
-                    let (program, args) = sub_group.split_first().unwrap();
-                    (
-                        program.to_string(),
-                        args.iter().map(|s| s.to_string()).collect(),
-                    )
-                })
-                .collect()
-        })
-        .collect();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn({
+        let registry = registry.clone();
+        async move {
+            wait_for_termination_signal().await;
+            // Ignore send errors: if every receiver is already gone, the program is
+            // shutting down anyway.
+            let _ = shutdown_tx.send(true);
+            // Process-group isolation means the signal we just caught never reached
+            // the children on its own; reach them ourselves instead of just hoping
+            // a restart loop notices the flag before spawny exits.
+            kill_all_processes(&registry, kill_timeout).await;
+        }
+    });
 
-    if let Err(e) = execute_process_chains(process_chains).await {
+    let config = RunConfig {
+        kill_timeout,
+        shell,
+        output,
+        line_tx: None,
+        restart,
+        max_restarts,
+        restart_backoff,
+        shutdown: shutdown_rx,
+    };
+
+    let process_chains = parse_process_chains(&commands, separator, &seq_separator);
+
+    if let Err(e) = execute_process_chains(process_chains, config, registry).await {
         eprintln!("{:#}", e);
         std::process::exit(1);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toks(tokens: &[&str]) -> Vec<String> {
+        tokens.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn shell_parse_recognizes_builtins() {
+        assert_eq!(Shell::parse("none"), Shell::None);
+        assert_eq!(Shell::parse("cmd"), Shell::Cmd);
+        assert_eq!(Shell::parse("powershell"), Shell::Pwsh);
+    }
+
+    #[test]
+    fn shell_parse_treats_unknown_value_as_unix_shell_name() {
+        assert_eq!(Shell::parse("zsh"), Shell::Unix("zsh".to_string()));
+    }
+
+    #[test]
+    fn shell_command_passes_through_when_no_shell() {
+        let (program, args) = shell_command("echo", &toks(&["hi"]), &Shell::None);
+        assert_eq!(program, "echo");
+        assert_eq!(args, toks(&["hi"]));
+    }
+
+    #[test]
+    fn shell_command_rejoins_and_wraps_for_unix_shell() {
+        let (program, args) = shell_command("echo", &toks(&["a", "b"]), &Shell::Unix("sh".to_string()));
+        assert_eq!(program, "sh");
+        assert_eq!(args, toks(&["-c", "echo a b"]));
+    }
+
+    #[test]
+    fn shell_command_wraps_for_cmd() {
+        let (program, args) = shell_command("dir", &toks(&["/w"]), &Shell::Cmd);
+        assert_eq!(program, "cmd");
+        assert_eq!(args, toks(&["/C", "dir /w"]));
+    }
+
+    #[test]
+    fn shell_command_wraps_for_pwsh() {
+        let (program, args) = shell_command("ls", &[], &Shell::Pwsh);
+        assert_eq!(program, "powershell");
+        assert_eq!(args, toks(&["-Command", "ls"]));
+    }
+
+    #[test]
+    fn restart_policy_parse_recognizes_known_values() {
+        assert_eq!(RestartPolicy::parse("never").unwrap(), RestartPolicy::Never);
+        assert_eq!(RestartPolicy::parse("on-failure").unwrap(), RestartPolicy::OnFailure);
+        assert_eq!(RestartPolicy::parse("always").unwrap(), RestartPolicy::Always);
+    }
+
+    #[test]
+    fn restart_policy_parse_rejects_unknown_value() {
+        assert!(RestartPolicy::parse("sometimes").is_err());
+    }
+
+    #[test]
+    fn parse_process_chains_splits_parallel_chains() {
+        let commands = toks(&["hello", "::", "world", "--doit"]);
+        let chains = parse_process_chains(&commands, "::", "::::");
+        assert_eq!(
+            chains,
+            vec![
+                vec![vec![("hello".to_string(), vec![])]],
+                vec![vec![("world".to_string(), toks(&["--doit"]))]],
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_process_chains_splits_sequential_steps_within_a_chain() {
+        let commands = toks(&["server", "::::", "sleep", "2", "::::", "client"]);
+        let chains = parse_process_chains(&commands, "::", "::::");
+        assert_eq!(
+            chains,
+            vec![vec![
+                vec![("server".to_string(), vec![])],
+                vec![("sleep".to_string(), toks(&["2"]))],
+                vec![("client".to_string(), vec![])],
+            ]]
+        );
+    }
+
+    #[test]
+    fn parse_process_chains_splits_pipeline_stages_within_a_step() {
+        let commands = toks(&["producer", "|", "consumer"]);
+        let chains = parse_process_chains(&commands, "::", "::::");
+        assert_eq!(
+            chains,
+            vec![vec![vec![
+                ("producer".to_string(), vec![]),
+                ("consumer".to_string(), vec![]),
+            ]]]
+        );
+    }
+
+    #[test]
+    fn parse_process_chains_ignores_stray_separators() {
+        let commands = toks(&["::", "hello", "::"]);
+        let chains = parse_process_chains(&commands, "::", "::::");
+        assert_eq!(chains, vec![vec![vec![("hello".to_string(), vec![])]]]);
+    }
+}
@@ -0,0 +1,117 @@
+//! Line-buffered, prefixed, per-process colorized output multiplexing.
+//!
+//! When enabled, each process's stdout/stderr is piped and forwarded line-by-line
+//! through a channel to a single printer task, which prefixes every line with the
+//! program's name in a stable per-process color so that interleaved output from
+//! parallel chains stays readable.
+
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{ChildStderr, ChildStdout};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+
+const COLORS: &[&str] = &[
+    "\x1b[36m", // cyan
+    "\x1b[32m", // green
+    "\x1b[33m", // yellow
+    "\x1b[35m", // magenta
+    "\x1b[34m", // blue
+    "\x1b[31m", // red
+];
+const RESET: &str = "\x1b[0m";
+
+static NEXT_COLOR: AtomicUsize = AtomicUsize::new(0);
+
+/// Assigns the next color in the round-robin palette to a newly spawned process.
+pub fn next_color() -> &'static str {
+    let idx = NEXT_COLOR.fetch_add(1, Ordering::Relaxed) % COLORS.len();
+    COLORS[idx]
+}
+
+/// Whether, and how, output should be prefixed and colorized.
+#[derive(Clone, Copy, Debug)]
+pub struct OutputOptions {
+    pub prefix: bool,
+    pub color: bool,
+}
+
+impl OutputOptions {
+    pub fn new(no_prefix: bool, no_color: bool, raw: bool) -> Self {
+        let prefix = !no_prefix && !raw;
+        let color = prefix && !no_color && std::io::stdout().is_terminal();
+        Self { prefix, color }
+    }
+}
+
+/// A single line read from a child's stdout or stderr, tagged with its source.
+pub struct Line {
+    pub label: String,
+    pub color: &'static str,
+    pub is_stderr: bool,
+    pub text: String,
+}
+
+/// Spawns a channel and the printer task that drains it; returns the sender to
+/// hand to each process's reader tasks and the printer's join handle.
+pub fn spawn_printer(color: bool) -> (UnboundedSender<Line>, JoinHandle<()>) {
+    let (tx, mut rx): (_, UnboundedReceiver<Line>) = mpsc::unbounded_channel();
+    let handle = tokio::spawn(async move {
+        while let Some(line) = rx.recv().await {
+            let formatted = if color {
+                format!("{}[{}]{} {}", line.color, line.label, RESET, line.text)
+            } else {
+                format!("[{}] {}", line.label, line.text)
+            };
+            if line.is_stderr {
+                eprintln!("{}", formatted);
+            } else {
+                println!("{}", formatted);
+            }
+        }
+    });
+    (tx, handle)
+}
+
+/// Spawns the reader tasks that forward a process's stdout/stderr lines to the
+/// printer channel, tagged with `label` and `color`.
+pub fn spawn_readers(
+    label: String,
+    color: &'static str,
+    stdout: ChildStdout,
+    stderr: ChildStderr,
+    tx: UnboundedSender<Line>,
+) {
+    let stdout_label = label.clone();
+    let stdout_tx = tx.clone();
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Ok(Some(text)) = lines.next_line().await {
+            let _ = stdout_tx.send(Line {
+                label: stdout_label.clone(),
+                color,
+                is_stderr: false,
+                text,
+            });
+        }
+    });
+
+    spawn_stderr_reader(label, color, stderr, tx);
+}
+
+/// Spawns just the stderr reader task, for stages whose stdout is going
+/// somewhere else (e.g. piped into the next stage of a pipeline).
+pub fn spawn_stderr_reader(label: String, color: &'static str, stderr: ChildStderr, tx: UnboundedSender<Line>) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stderr).lines();
+        while let Ok(Some(text)) = lines.next_line().await {
+            let _ = tx.send(Line {
+                label: label.clone(),
+                color,
+                is_stderr: true,
+                text,
+            });
+        }
+    });
+}
@@ -0,0 +1,15 @@
+//! Platform-specific process group handling.
+//!
+//! Unix tracks a process group id and signals the whole group. Windows has no
+//! equivalent to process groups for arbitrary child trees, so each child is
+//! instead assigned to a Job Object, which is terminated as a unit.
+
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(unix)]
+pub use unix::*;
+#[cfg(windows)]
+pub use windows::*;
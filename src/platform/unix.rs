@@ -0,0 +1,32 @@
+use anyhow::{Context, Result};
+use tokio::process::{Child, Command};
+
+/// A handle identifying a spawned child's process group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProcessHandle(i32);
+
+/// Makes the child the leader of a new process group so that `terminate` can
+/// reach the whole subtree instead of just the immediate child.
+pub fn configure_spawn(command: &mut Command) {
+    // `process_group` is an inherent method on `tokio::process::Command`, not a
+    // `CommandExt` trait method, so no extra import is needed here.
+    command.process_group(0);
+}
+
+/// Records the process group id (equal to the pid, since the child is its own
+/// group leader) of a freshly spawned child.
+pub fn register(child: &Child) -> Result<ProcessHandle> {
+    let pid = child.id().context("Failed to get process ID")?;
+    Ok(ProcessHandle(pid as i32))
+}
+
+/// Signals the process group: `SIGTERM` on the first pass, `SIGKILL` once `force` is set.
+pub fn terminate(handle: ProcessHandle, force: bool) {
+    let signal = if force { libc::SIGKILL } else { libc::SIGTERM };
+    unsafe {
+        libc::killpg(handle.0, signal);
+    }
+}
+
+/// No-op on Unix: a process group id isn't a kernel handle, so there's nothing to close.
+pub fn release(_handle: ProcessHandle) {}
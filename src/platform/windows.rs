@@ -0,0 +1,60 @@
+use anyhow::{Context, Result};
+use tokio::process::{Child, Command};
+use windows_sys::Win32::Foundation::CloseHandle;
+use windows_sys::Win32::System::JobObjects::{AssignProcessToJobObject, CreateJobObjectW};
+use windows_sys::Win32::System::JobObjects::TerminateJobObject;
+use windows_sys::Win32::System::Threading::{OpenProcess, PROCESS_ALL_ACCESS};
+
+/// A handle to the Job Object a child (and anything it spawns) has been assigned to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProcessHandle(isize);
+
+// The raw HANDLE is just an opaque kernel object reference; it's fine to move
+// between the tasks that register and terminate it.
+unsafe impl Send for ProcessHandle {}
+unsafe impl Sync for ProcessHandle {}
+
+/// No-op on Windows: the job assignment happens after spawn, once the child's
+/// process handle is available.
+pub fn configure_spawn(_command: &mut Command) {}
+
+/// Creates a Job Object and assigns the freshly spawned child to it, so that
+/// terminating the job terminates the child and everything it spawns.
+pub fn register(child: &Child) -> Result<ProcessHandle> {
+    let pid = child.id().context("Failed to get process ID")?;
+    unsafe {
+        let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+        if job == 0 {
+            anyhow::bail!("Failed to create job object for process {}", pid);
+        }
+        let process = OpenProcess(PROCESS_ALL_ACCESS, 0, pid);
+        if process == 0 {
+            CloseHandle(job);
+            anyhow::bail!("Failed to open process {}", pid);
+        }
+        let assigned = AssignProcessToJobObject(job, process);
+        CloseHandle(process);
+        if assigned == 0 {
+            CloseHandle(job);
+            anyhow::bail!("Failed to assign process {} to job object", pid);
+        }
+        Ok(ProcessHandle(job as isize))
+    }
+}
+
+/// Terminates the whole job. Windows has no `SIGTERM`/`SIGKILL` distinction, so
+/// both phases of `kill_all_processes` end up calling this; the second call is
+/// a harmless no-op once the job has already been terminated.
+pub fn terminate(handle: ProcessHandle, _force: bool) {
+    unsafe {
+        TerminateJobObject(handle.0 as _, 1);
+    }
+}
+
+/// Closes the Job Object handle once it's no longer tracked anywhere, so a
+/// long-running supervised step doesn't leak one kernel handle per restart.
+pub fn release(handle: ProcessHandle) {
+    unsafe {
+        CloseHandle(handle.0 as _);
+    }
+}